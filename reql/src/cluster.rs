@@ -0,0 +1,69 @@
+//! Multi-node cluster support for [`Session`](crate::Session).
+//!
+//! A [`Session`](crate::Session) used to dial exactly one host. Here it holds
+//! one [`ConnectionPool`] per node (the connect host, plus any node added by
+//! [`Session::discover_nodes`](crate::Session::discover_nodes)) and consults a
+//! [`LoadBalancingPolicy`] to pick a node for each new token, so the loss of
+//! any single node doesn't take the whole session down with it.
+
+use crate::pool::ConnectionPool;
+use crate::proto::Query;
+use std::net::SocketAddr;
+
+/// Index of a node within a [`Session`](crate::Session)'s node list.
+pub type NodeIndex = usize;
+
+/// A single reachable RethinkDB server, as seen by the driver.
+#[derive(Debug)]
+pub struct Node {
+    pub(crate) addr: SocketAddr,
+    pub(crate) pool: ConnectionPool,
+}
+
+impl Node {
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+/// Chooses which node a new token's traffic is registered on.
+///
+/// Implementations see the query being run so they can, for example, route
+/// reads and writes differently; a query-agnostic policy like
+/// [`RoundRobinPolicy`] simply ignores it.
+pub trait LoadBalancingPolicy: std::fmt::Debug + Send + Sync {
+    /// Picks a node out of `nodes`, or `None` if `nodes` is empty.
+    /// `query` is `None` for driver-internal calls (e.g.
+    /// [`Session::server`](crate::Session::server)) that aren't tied to a
+    /// particular user query.
+    fn pick(&self, nodes: &[Node], query: Option<&Query>) -> Option<NodeIndex>;
+}
+
+/// Spreads tokens evenly across all live nodes in turn.
+#[derive(Debug, Default)]
+pub struct RoundRobinPolicy {
+    next: std::sync::atomic::AtomicUsize,
+}
+
+impl RoundRobinPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl LoadBalancingPolicy for RoundRobinPolicy {
+    fn pick(&self, nodes: &[Node], _query: Option<&Query>) -> Option<NodeIndex> {
+        use std::sync::atomic::Ordering;
+        if nodes.is_empty() {
+            return None;
+        }
+        let len = nodes.len();
+        for _ in 0..len {
+            let idx = self.next.fetch_add(1, Ordering::Relaxed) % len;
+            if !nodes[idx].pool.is_broken() {
+                return Some(idx);
+            }
+        }
+        Some(self.next.fetch_add(1, Ordering::Relaxed) % len)
+    }
+}
@@ -0,0 +1,49 @@
+//! Query-builder terms and the commands that operate on a [`Session`](crate::Session)
+//! or [`Connection`](crate::Connection) directly.
+//!
+//! Only the connection-lifecycle commands ([`connect`], [`run`], [`close`])
+//! live in this snapshot; the full ReQL term DSL (`table`, `filter`, `map`,
+//! ...) is assumed to exist alongside them, same as the rest of this crate's
+//! `cmd::*` references.
+
+pub mod close;
+pub mod connect;
+pub mod run;
+
+use std::borrow::Cow;
+use std::str;
+
+/// Converts an owned or borrowed string into a `'static`-friendly [`Cow`],
+/// so `Session`/`Options` builders can accept either without an allocation
+/// in the common `&'static str` case.
+pub trait StaticString {
+    fn static_string(self) -> Cow<'static, str>;
+}
+
+impl StaticString for &'static str {
+    fn static_string(self) -> Cow<'static, str> {
+        Cow::from(self)
+    }
+}
+
+impl StaticString for String {
+    fn static_string(self) -> Cow<'static, str> {
+        Cow::from(self)
+    }
+}
+
+impl StaticString for &Cow<'static, str> {
+    fn static_string(self) -> Cow<'static, str> {
+        match self {
+            Cow::Borrowed(string) => Cow::Borrowed(*string),
+            Cow::Owned(string) => Cow::Owned(string.to_owned()),
+        }
+    }
+}
+
+pub(crate) fn debug(bytes: &[u8]) -> String {
+    if let Ok(string) = str::from_utf8(bytes) {
+        return string.to_owned();
+    }
+    format!("{:?}", bytes)
+}
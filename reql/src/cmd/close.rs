@@ -0,0 +1,32 @@
+//! Close an open connection.
+//!
+//! Closing stops the changefeed bound to the connection, if there is one,
+//! by sending a `QueryType::Stop` for its token; on a connection without an
+//! open changefeed it does nothing. It does not wait for outstanding
+//! `noreply` writes: passing [`SkipNoreplyWait`] as the argument only adds a
+//! `{"noreply": false}` argument to the stop query.
+//!
+//! ## Related commands
+//! * [connect](crate::r::connect)
+//! * [use](crate::Session::use)
+
+/// Sends the stop query with a `{"noreply": false}` argument; see the
+/// module docs.
+#[derive(Debug)]
+pub struct SkipNoreplyWait;
+
+pub trait Arg {
+    fn noreply_wait(self) -> bool;
+}
+
+impl Arg for () {
+    fn noreply_wait(self) -> bool {
+        true
+    }
+}
+
+impl Arg for SkipNoreplyWait {
+    fn noreply_wait(self) -> bool {
+        false
+    }
+}
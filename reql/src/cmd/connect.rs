@@ -0,0 +1,317 @@
+//! Open a new connection to the database server.
+
+use super::StaticString;
+use crate::auth::{AuthenticatorProvider, ScramClient, StaticCredentials};
+use crate::cluster::{Node, RoundRobinPolicy};
+use crate::pool::{ConnectionPool, Transport};
+use crate::retry::DefaultRetryPolicy;
+use crate::{err, Result, Session};
+use dashmap::DashMap;
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+use log::trace;
+use ql2::version_dummy::Version;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, AtomicU64};
+
+const BUF_SIZE: usize = 1024;
+const NULL_BYTE: u8 = b'\0';
+const PROTOCOL_VERSION: usize = 0;
+
+pub(crate) const DEFAULT_DB: &str = "test";
+const DEFAULT_POOL_SIZE: usize = 4;
+
+/// Options accepted by [`r::connect`](crate::r::connect).
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Options {
+    pub host: Cow<'static, str>,
+    pub port: u16,
+    /// The database used if not explicitly specified in a query, by default `test`.
+    pub db: Cow<'static, str>,
+    /// The user account to connect as (default `admin`).
+    pub user: Cow<'static, str>,
+    /// The password for the user account to connect as (default `""`, empty).
+    pub password: Cow<'static, str>,
+    /// Number of physical connections to keep open to the node, by default 4.
+    pub pool_size: usize,
+    /// Supplies the credentials used for the SCRAM-SHA-256 handshake. When
+    /// unset, a [`StaticCredentials`] built from [`user`](Self::user) and
+    /// [`password`](Self::password) is used; set this instead to plug in
+    /// credentials from somewhere other than an in-memory string.
+    pub authenticator: Option<Box<dyn AuthenticatorProvider>>,
+    /// Connect over TLS instead of plain TCP, using this configuration.
+    /// Unset by default, meaning a plain TCP connection.
+    #[cfg(feature = "rustls")]
+    pub tls: Option<crate::tls::TlsConfig>,
+}
+
+impl Options {
+    /// Create new options from default values
+    pub fn new() -> Self {
+        Self {
+            host: "localhost".static_string(),
+            port: 28015,
+            db: DEFAULT_DB.static_string(),
+            user: "admin".static_string(),
+            password: "".static_string(),
+            pool_size: DEFAULT_POOL_SIZE,
+            authenticator: None,
+            #[cfg(feature = "rustls")]
+            tls: None,
+        }
+    }
+
+    /// Use a custom [`AuthenticatorProvider`] instead of the default
+    /// [`StaticCredentials`] built from [`user`](Self::user)/[`password`](Self::password).
+    pub fn authenticator<A: AuthenticatorProvider + 'static>(mut self, authenticator: A) -> Self {
+        self.authenticator = Some(Box::new(authenticator));
+        self
+    }
+
+    /// Connect over TLS instead of plain TCP.
+    #[cfg(feature = "rustls")]
+    pub fn tls(mut self, tls: crate::tls::TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Set the database used if not explicitly specified in a query, by default `test`.
+    pub fn db<T: StaticString>(mut self, db: T) -> Self {
+        self.db = db.static_string();
+        self
+    }
+
+    /// Set the user account to connect as (default `admin`).
+    pub fn user<T: StaticString>(mut self, user: T) -> Self {
+        self.user = user.static_string();
+        self
+    }
+
+    /// Set the password for the user account to connect as (default `""`, empty).
+    pub fn password<T: StaticString>(mut self, password: T) -> Self {
+        self.password = password.static_string();
+        self
+    }
+
+    /// Set the number of physical connections kept open to the node.
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size.max(1);
+        self
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The arguments accepted by [`r::connect`](crate::r::connect).
+pub trait Arg {
+    fn into_connect_opts(self) -> Options;
+}
+
+impl Arg for () {
+    fn into_connect_opts(self) -> Options {
+        Options::new()
+    }
+}
+
+impl Arg for Options {
+    fn into_connect_opts(self) -> Options {
+        self
+    }
+}
+
+impl Arg for &'static str {
+    fn into_connect_opts(self) -> Options {
+        Options::new().db(self)
+    }
+}
+
+pub(crate) async fn new(options: Options) -> Result<Session> {
+    let addr = async_net::resolve((options.host.as_ref(), options.port))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| err::Driver::Other(format!("couldn't resolve {}:{}", options.host, options.port)))?;
+
+    #[cfg(feature = "rustls")]
+    let transport = match &options.tls {
+        Some(tls) => Transport::Tls(tls.clone()),
+        None => Transport::Plain,
+    };
+    #[cfg(not(feature = "rustls"))]
+    let transport = Transport::Plain;
+
+    let pool = ConnectionPool::connect(addr, options.pool_size, transport.clone()).await?;
+    let authenticator: Box<dyn AuthenticatorProvider> = match options.authenticator {
+        Some(authenticator) => authenticator,
+        None => Box::new(StaticCredentials::new(options.user.clone(), options.password.clone())),
+    };
+    handshake_pool(&pool, authenticator.as_ref()).await?;
+
+    Ok(Session {
+        db: options.db,
+        nodes: vec![Node { addr, pool }],
+        pool_size: options.pool_size,
+        transport,
+        lb_policy: Box::new(RoundRobinPolicy::new()),
+        retry_policy: Box::new(DefaultRetryPolicy::default()),
+        #[cfg(feature = "metrics")]
+        metrics: crate::metrics::Metrics::new(),
+        channels: DashMap::new(),
+        token: AtomicU64::new(0),
+        exhausted: AtomicBool::new(false),
+        change_feed: AtomicBool::new(false),
+        authenticator,
+    })
+}
+
+/// Performs the V1.0 handshake (SCRAM-SHA-256) on every socket in `pool`.
+///
+/// RethinkDB authenticates per-socket, so a pool with more than one
+/// connection needs the exchange run on each of them before any query is
+/// written to it; a socket the server is still waiting to greet will desync
+/// the moment a query frame lands on it. They all use the same credentials,
+/// so the same `authenticator` is reused across sockets, but each gets its
+/// own [`ScramClient`] since the nonce it generates must be per-connection.
+pub(crate) async fn handshake_pool(pool: &ConnectionPool, authenticator: &dyn AuthenticatorProvider) -> Result<()> {
+    for idx in 0..pool.len() {
+        handshake(pool.get(idx), authenticator).await?;
+    }
+    Ok(())
+}
+
+/// Performs the V1.0 handshake (SCRAM-SHA-256) on a single pooled socket.
+/// The actual SCRAM exchange lives in
+/// [`auth::ScramClient`](crate::auth::ScramClient); this function is just the
+/// wire plumbing (framing, reading) around it.
+pub(crate) async fn handshake(conn: &crate::pool::PooledConnection, authenticator: &dyn AuthenticatorProvider) -> Result<()> {
+    let mut writer = conn.writer.lock().await;
+    let mut reader = conn.reader.lock().await;
+    let mut scram = ScramClient::new();
+
+    trace!("sending supported version to RethinkDB");
+    writer.write_all(&(Version::V10 as i32).to_le_bytes()).await?;
+
+    let ar = AuthRequest {
+        protocol_version: PROTOCOL_VERSION,
+        authentication_method: "SCRAM-SHA-256",
+        authentication: scram.client_first_message(authenticator.username()),
+    };
+    let mut msg = serde_json::to_vec(&ar)?;
+    msg.push(NULL_BYTE);
+    trace!("sending client first message");
+    writer.write_all(&msg).await?;
+
+    let mut buf = [0u8; BUF_SIZE];
+    trace!("receiving message(s) from RethinkDB");
+    reader.read(&mut buf).await?;
+    let (len, resp) = framed(&buf, 0);
+    ServerInfo::validate(resp)?;
+
+    let offset = len + 1;
+    let resp = if offset < BUF_SIZE && buf[offset] != NULL_BYTE {
+        framed(&buf, offset).1.to_vec()
+    } else {
+        reader.read(&mut buf).await?;
+        framed(&buf, 0).1.to_vec()
+    };
+    let info = AuthResponse::from_slice(&resp)?;
+    let server_first = info
+        .authentication
+        .ok_or_else(|| err::Driver::Authentication("server did not send authentication info".into()))?;
+
+    let client_final =
+        scram.handle_server_first(authenticator.username(), authenticator.password(), &server_first)?;
+    let conf = AuthConfirmation {
+        authentication: client_final,
+    };
+    let mut msg = serde_json::to_vec(&conf)?;
+    msg.push(NULL_BYTE);
+    trace!("sending client final message");
+    writer.write_all(&msg).await?;
+
+    trace!("reading server final message");
+    reader.read(&mut buf).await?;
+    let resp = framed(&buf, 0).1;
+    let info = AuthResponse::from_slice(resp)?;
+    if let Some(server_final) = info.authentication {
+        scram.verify_server_signature(&server_final)?;
+    }
+
+    trace!("client connected successfully");
+    Ok(())
+}
+
+fn framed(buf: &[u8], offset: usize) -> (usize, &[u8]) {
+    let len = buf[offset..].iter().take_while(|x| **x != NULL_BYTE).count();
+    let max = offset + len;
+    (max, &buf[offset..max])
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ServerInfo<'a> {
+    success: bool,
+    min_protocol_version: usize,
+    max_protocol_version: usize,
+    #[allow(dead_code)]
+    server_version: &'a str,
+}
+
+impl ServerInfo<'_> {
+    fn validate(resp: &[u8]) -> Result<()> {
+        let info = serde_json::from_slice::<ServerInfo>(resp)?;
+        if !info.success {
+            return Err(err::Driver::Other(super::debug(resp)).into());
+        }
+        #[allow(clippy::absurd_extreme_comparisons)]
+        if PROTOCOL_VERSION < info.min_protocol_version || info.max_protocol_version < PROTOCOL_VERSION {
+            let msg = format!(
+                "unsupported protocol version {version}, expected between {min} and {max}",
+                version = PROTOCOL_VERSION,
+                min = info.min_protocol_version,
+                max = info.max_protocol_version,
+            );
+            return Err(err::Driver::Other(msg).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AuthRequest {
+    protocol_version: usize,
+    authentication_method: &'static str,
+    authentication: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AuthConfirmation {
+    authentication: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AuthResponse {
+    success: bool,
+    authentication: Option<String>,
+    error_code: Option<usize>,
+    error: Option<String>,
+}
+
+impl AuthResponse {
+    fn from_slice(resp: &[u8]) -> Result<Self> {
+        let info = serde_json::from_slice::<AuthResponse>(resp)?;
+        if !info.success {
+            if let Some(10..=20) = info.error_code {
+                if let Some(msg) = info.error {
+                    return Err(err::Driver::Authentication(msg).into());
+                }
+            }
+            return Err(err::Driver::Other(super::debug(resp)).into());
+        }
+        Ok(info)
+    }
+}
@@ -0,0 +1,354 @@
+//! Run a query and read back its response.
+//!
+//! This is the one place that actually speaks the RethinkDB wire protocol:
+//! [`Connection::request`] frames a [`Payload`](crate::proto::Payload),
+//! writes it to whichever pooled socket the connection was handed at
+//! [`Session::connection`](crate::Session::connection) time, and parses the
+//! framed response that comes back.
+
+use crate::cluster::NodeIndex;
+use crate::cmd::StaticString;
+use crate::proto::Payload;
+use crate::retry::{Idempotency, RetryDecision};
+use crate::{err, Connection, Error, Result, Session};
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+use futures::stream::StreamExt;
+use log::trace;
+use ql2::response::{ErrorType, ResponseType};
+use ql2::Frame;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+
+const DATA_SIZE: usize = 4;
+const TOKEN_SIZE: usize = 8;
+const HEADER_SIZE: usize = DATA_SIZE + TOKEN_SIZE;
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct Response {
+    t: i32,
+    e: Option<i32>,
+    pub(crate) r: Value,
+    #[allow(dead_code)]
+    b: Option<Vec<Frame>>,
+    #[allow(dead_code)]
+    p: Option<Value>,
+    #[allow(dead_code)]
+    n: Option<Vec<i32>>,
+}
+
+impl Response {
+    fn new() -> Self {
+        Self {
+            t: ResponseType::SuccessAtom as i32,
+            e: None,
+            r: Value::Array(Vec::new()),
+            b: None,
+            p: None,
+            n: None,
+        }
+    }
+}
+
+/// Options accepted by [`Query::run`](crate::Query).
+#[derive(Debug, Clone, Serialize, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[non_exhaustive]
+pub struct Options {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_mode: Option<ReadMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noreply: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub db: Option<Db>,
+    /// Forces the query to be treated as idempotent for retry purposes even
+    /// if its top-level term isn't one of the ones
+    /// [`retry::Idempotency::of`](crate::retry::Idempotency::of) trusts by
+    /// default, e.g. a write the caller knows is naturally safe to repeat.
+    #[serde(skip)]
+    pub idempotent: Option<bool>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn noreply(mut self, noreply: bool) -> Self {
+        self.noreply = Some(noreply);
+        self
+    }
+
+    pub fn db<T: StaticString>(mut self, db: T) -> Self {
+        self.db = Some(Db(db.static_string()));
+        self
+    }
+
+    /// See [`Options::idempotent`](Self::idempotent) field doc.
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = Some(idempotent);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub enum ReadMode {
+    Single,
+    Majority,
+    Outdated,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Db(pub Cow<'static, str>);
+
+impl Payload {
+    fn encode(&self, token: u64) -> Result<Vec<u8>> {
+        let bytes = self.to_bytes()?;
+        let data_len = bytes.len();
+        let mut buf = Vec::with_capacity(HEADER_SIZE + data_len);
+        buf.extend_from_slice(&token.to_le_bytes());
+        buf.extend_from_slice(&(data_len as u32).to_le_bytes());
+        buf.extend_from_slice(&bytes);
+        Ok(buf)
+    }
+}
+
+impl Connection<'_> {
+    fn send_response(&self, db_token: u64, resp: Result<(ResponseType, Response)>) {
+        if let Some(tx) = self.session.channels.get(&db_token) {
+            if let Err(error) = tx.unbounded_send(resp) {
+                if error.is_disconnected() {
+                    self.session.channels.remove(&db_token);
+                }
+            }
+        }
+    }
+
+    /// Sends `query` over this connection's pooled socket and waits for the
+    /// matching response to come back through the session's demux channel.
+    ///
+    /// A connection-level failure (a broken socket, a timeout) is handed to
+    /// [`Session::retry_decision`](crate::Session) before it's surfaced to
+    /// the caller; an idempotent query may be retried on the same socket or
+    /// a freshly reconnected one, with backoff between attempts. When built
+    /// with the `metrics` feature, the whole call (every retry included) is
+    /// recorded once in [`Session::metrics`](crate::Session::metrics): as one
+    /// query, with its latency, when it succeeds, or as one error when it fails.
+    pub(crate) async fn request(
+        &mut self,
+        query: &Payload,
+        noreply: bool,
+    ) -> Result<(ResponseType, Response)> {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+
+        let result = self.request_retrying(query, noreply).await;
+
+        #[cfg(feature = "metrics")]
+        match &result {
+            Ok(_) => self.session.record_query(started.elapsed()),
+            Err(error) => self.session.record_error(error),
+        }
+
+        result
+    }
+
+    async fn request_retrying(
+        &mut self,
+        query: &Payload,
+        noreply: bool,
+    ) -> Result<(ResponseType, Response)> {
+        let idempotency = match &query.1 {
+            Some(term) => Idempotency::of(term.term_type(), query.2.idempotent.unwrap_or(false)),
+            None => Idempotency::Idempotent,
+        };
+
+        let mut attempt = 0;
+        loop {
+            self.submit(query, noreply).await;
+            let result = match self.rx.lock().await.next().await {
+                Some(resp) => resp,
+                None => return Ok((ResponseType::SuccessAtom, Response::new())),
+            };
+
+            let driver_err = match &result {
+                Err(Error::Driver(driver_err)) => driver_err.clone(),
+                _ => return result,
+            };
+
+            match self.session.retry_decision(idempotency, &driver_err, attempt) {
+                RetryDecision::DontRetry => return result,
+                decision => {
+                    let backoff = self.session.retry_backoff(attempt);
+                    if decision == RetryDecision::RetryNewConnection {
+                        let (node_index, pool_index) = self.pool_index();
+                        let pool = &self.session.nodes[node_index].pool;
+                        // The new socket must complete the same SCRAM handshake as the pool's
+                        // original sockets before a query frame is written to it.
+                        let reconnected = match pool.reconnect(pool_index).await {
+                            Ok(()) => {
+                                crate::cmd::connect::handshake(pool.get(pool_index), self.session.authenticator.as_ref())
+                                    .await
+                            }
+                            Err(error) => Err(error),
+                        };
+                        // A failed reconnect is itself a connection-level failure: leave the
+                        // attempt counter to advance below and let the next loop iteration's
+                        // submit (which will hit the still-broken socket) go back through
+                        // `retry_decision`, rather than short-circuiting the retry budget here.
+                        if let Err(error) = reconnected {
+                            pool.get(pool_index).mark_broken();
+                            trace!(
+                                "reconnect failed, will retry through the normal attempt budget; token: {}, attempt: {}, error: {}",
+                                self.token,
+                                attempt,
+                                error,
+                            );
+                        }
+                    }
+                    trace!(
+                        "retrying request after {:?}; token: {}, attempt: {}, error: {}",
+                        backoff,
+                        self.token,
+                        attempt,
+                        driver_err,
+                    );
+                    async_io::Timer::after(backoff).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn submit(&self, query: &Payload, noreply: bool) {
+        let mut db_token = self.token;
+        let result = self.exec(query, noreply, &mut db_token).await;
+        self.send_response(db_token, result);
+    }
+
+    async fn exec(
+        &self,
+        query: &Payload,
+        noreply: bool,
+        db_token: &mut u64,
+    ) -> Result<(ResponseType, Response)> {
+        let buf = query.encode(self.token)?;
+
+        let (node_index, pool_index) = self.pool_index();
+        let node = &self.session.nodes[node_index];
+        let pooled = node.pool.get(pool_index);
+
+        trace!("sending query; token: {}, payload: {}", self.token, query);
+        let written = {
+            let mut writer = pooled.writer.lock().await;
+            writer.write_all(&buf).await
+        };
+        if written.is_err() {
+            pooled.mark_broken();
+            return Err(err::Driver::ConnectionBroken.into());
+        }
+        trace!("query sent; token: {}", self.token);
+
+        if noreply {
+            return Ok((ResponseType::SuccessAtom, Response::new()));
+        }
+
+        let mut reader = pooled.reader.lock().await;
+
+        trace!("reading header; token: {}", self.token);
+        let mut header = [0u8; HEADER_SIZE];
+        if reader.read_exact(&mut header).await.is_err() {
+            pooled.mark_broken();
+            return Err(err::Driver::ConnectionBroken.into());
+        }
+
+        let mut buf = [0u8; TOKEN_SIZE];
+        buf.copy_from_slice(&header[..TOKEN_SIZE]);
+        *db_token = u64::from_le_bytes(buf);
+        trace!("db_token: {}", db_token);
+
+        let mut buf = [0u8; DATA_SIZE];
+        buf.copy_from_slice(&header[TOKEN_SIZE..]);
+        let len = u32::from_le_bytes(buf) as usize;
+        trace!(
+            "header read; token: {}, db_token: {}, response_len: {}",
+            self.token,
+            db_token,
+            len
+        );
+
+        trace!("reading body; token: {}", self.token);
+        let mut buf = vec![0u8; len];
+        if reader.read_exact(&mut buf).await.is_err() {
+            pooled.mark_broken();
+            return Err(err::Driver::ConnectionBroken.into());
+        }
+
+        trace!(
+            "body read; token: {}, db_token: {}, body: {}",
+            self.token,
+            db_token,
+            super::debug(&buf),
+        );
+
+        let resp = serde_json::from_slice::<Response>(&buf)?;
+        trace!("response successfully parsed; token: {}", self.token);
+
+        let response_type = ResponseType::from_i32(resp.t)
+            .ok_or_else(|| err::Driver::Other(format!("unknown response type `{}`", resp.t)))?;
+
+        if let Some(error_type) = resp.e {
+            let msg = error_message(resp.r)?;
+            return Err(response_error(response_type, Some(error_type), msg));
+        }
+
+        Ok((response_type, resp))
+    }
+}
+
+/// Writes a `QueryType::Stop` frame for `token` directly to its pooled
+/// socket, without registering a channel for it.
+///
+/// The matching response still carries `token` and is read back by whatever
+/// [`Connection::request`] already has that token's channel registered;
+/// [`CancelToken::cancel`](crate::CancelToken::cancel) only needs to put the
+/// bytes on the wire, so this bypasses `Connection` entirely rather than
+/// standing up a second one that would steal (and, on drop, tear down) the
+/// original connection's channel registration.
+pub(crate) async fn send_stop(
+    session: &Session,
+    node_index: NodeIndex,
+    pool_index: usize,
+    token: u64,
+    query: &Payload,
+) -> Result<()> {
+    let buf = query.encode(token)?;
+    let pooled = session.nodes[node_index].pool.get(pool_index);
+    let mut writer = pooled.writer.lock().await;
+    trace!("sending cancellation; token: {}, payload: {}", token, query);
+    if writer.write_all(&buf).await.is_err() {
+        pooled.mark_broken();
+        return Err(err::Driver::ConnectionBroken.into());
+    }
+    Ok(())
+}
+
+fn error_message(response: Value) -> Result<String> {
+    let messages = serde_json::from_value::<Vec<String>>(response)?;
+    Ok(messages.join(" "))
+}
+
+fn response_error(response_type: ResponseType, error_type: Option<i32>, msg: String) -> err::Error {
+    match response_type {
+        ResponseType::RuntimeError => match error_type.map(ErrorType::from_i32) {
+            Some(Some(ErrorType::PermissionError)) => {
+                err::Driver::Authentication(msg).into()
+            }
+            _ => err::Driver::Other(msg).into(),
+        },
+        _ => err::Driver::Other(msg).into(),
+    }
+}
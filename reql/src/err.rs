@@ -0,0 +1,78 @@
+//! Driver-level error types.
+
+use std::fmt;
+
+/// Custom error returned by various ReQL commands.
+#[derive(Debug)]
+pub enum Error {
+    Driver(Driver),
+    Serde(serde_json::Error),
+    Io(std::io::Error),
+}
+
+/// Driver-level failures, as opposed to errors reported by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Driver {
+    /// A socket failed while a request was being written or read, or every
+    /// connection (for a cluster session, every node) is down.
+    ConnectionBroken,
+    /// The session's node list is empty, so there's nothing for a
+    /// [`LoadBalancingPolicy`](crate::cluster::LoadBalancingPolicy) to pick from.
+    NoNodesAvailable,
+    /// Called on a connection that already has a changefeed bound to it.
+    ConnectionLocked,
+    /// A request timed out. The driver's own request path sets no timeout
+    /// and never returns this variant.
+    TimedOut,
+    /// The SCRAM-SHA-256 handshake failed: bad credentials, a malformed
+    /// server message, or a server signature that didn't verify. Also
+    /// returned when the server rejects a query with a permission error.
+    /// Kept distinct from [`Driver::ConnectionBroken`] so callers can tell
+    /// "wrong password" apart from "the network is down".
+    Authentication(String),
+    /// Anything else.
+    Other(String),
+}
+
+impl fmt::Display for Driver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Driver::ConnectionBroken => write!(f, "connection broken"),
+            Driver::NoNodesAvailable => write!(f, "no nodes available"),
+            Driver::ConnectionLocked => write!(f, "connection locked by an open changefeed"),
+            Driver::TimedOut => write!(f, "request timed out"),
+            Driver::Authentication(msg) => write!(f, "authentication failed: {}", msg),
+            Driver::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Driver(err) => write!(f, "{}", err),
+            Error::Serde(err) => write!(f, "{}", err),
+            Error::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Driver> for Error {
+    fn from(err: Driver) -> Self {
+        Error::Driver(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Serde(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
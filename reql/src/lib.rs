@@ -62,11 +62,19 @@
 
 #![allow(clippy::wrong_self_convention)]
 
+pub mod auth;
+pub mod cluster;
 pub mod cmd;
 mod err;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod pool;
 mod proto;
+pub mod retry;
+#[cfg(feature = "rustls")]
+pub mod tls;
 
-use async_net::TcpStream;
+use cluster::{LoadBalancingPolicy, Node};
 use cmd::run::Response;
 use cmd::StaticString;
 use dashmap::DashMap;
@@ -79,6 +87,7 @@ use ql2::response::ResponseType;
 use ql2::term::TermType;
 use serde_json::json;
 use std::borrow::Cow;
+use std::net::SocketAddr;
 use std::ops::Drop;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
@@ -96,14 +105,36 @@ type Sender = UnboundedSender<Result<(ResponseType, Response)>>;
 type Receiver = UnboundedReceiver<Result<(ResponseType, Response)>>;
 
 /// The connection object returned by `r.connect()`
+///
+/// A session starts with one [`cluster::Node`] (and its own connection pool)
+/// for the host given in [`cmd::connect::Options`], which takes a single
+/// host and port, not a list of seed addresses;
+/// [`discover_nodes`](Self::discover_nodes) adds a node for each further
+/// server the cluster reports. Which node serves a given token is decided
+/// by the session's [`cluster::LoadBalancingPolicy`],
+/// [`cluster::RoundRobinPolicy`] by default.
 #[derive(Debug)]
 pub struct Session {
     db: Cow<'static, str>,
-    stream: Mutex<TcpStream>,
+    nodes: Vec<Node>,
+    /// Pool size and transport used to dial any node, including ones a
+    /// later [`discover_nodes`](Self::discover_nodes) call adds, so every
+    /// node in the session is configured consistently.
+    pool_size: usize,
+    transport: pool::Transport,
+    lb_policy: Box<dyn LoadBalancingPolicy>,
+    retry_policy: Box<dyn retry::RetryPolicy>,
+    #[cfg(feature = "metrics")]
+    metrics: metrics::Metrics,
     channels: DashMap<u64, Sender>,
     token: AtomicU64,
-    broken: AtomicBool,
+    exhausted: AtomicBool,
     change_feed: AtomicBool,
+    /// Credentials used to authenticate every socket in every node's pool,
+    /// including ones a later [`discover_nodes`](Self::discover_nodes) call
+    /// dials, so newly discovered nodes can complete the same handshake the
+    /// session was opened with.
+    authenticator: Box<dyn auth::AuthenticatorProvider>,
 }
 
 impl Session {
@@ -113,18 +144,68 @@ impl Session {
             .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| Some(x + 1))
             .unwrap();
         if token == u64::MAX {
-            self.mark_broken();
+            self.exhausted.store(true, Ordering::SeqCst);
         }
         token
     }
 
     pub fn connection(&self) -> Result<Connection<'_>> {
+        self.connection_for(None)
+    }
+
+    /// Like [`connection`](Self::connection) but lets the
+    /// [`LoadBalancingPolicy`] see the query it's being opened for, so
+    /// policies can route reads and writes to different nodes.
+    pub(crate) fn connection_for(&self, query: Option<&Query>) -> Result<Connection<'_>> {
         self.broken()?;
         self.change_feed()?;
+        let node_index = self
+            .lb_policy
+            .pick(&self.nodes, query)
+            .ok_or(err::Driver::NoNodesAvailable)?;
         let token = self.token();
         let (tx, rx) = mpsc::unbounded();
         self.channels.insert(token, tx);
-        Ok(Connection::new(self, rx, token))
+        let pool_index = self.nodes[node_index].pool.pick();
+        Ok(Connection::new(self, rx, token, node_index, pool_index))
+    }
+
+    /// The driver's current view of reachable nodes, similar in spirit to
+    /// [`server`](Self::server) but describing the whole cluster rather
+    /// than a single connection.
+    pub fn live_nodes(&self) -> Vec<SocketAddr> {
+        self.nodes
+            .iter()
+            .filter(|node| !node.pool.is_broken())
+            .map(Node::addr)
+            .collect()
+    }
+
+    /// Consults the session's [`retry::RetryPolicy`] for a failed request.
+    /// Changefeeds are excluded up front since their cursors aren't
+    /// replayable — retrying one would silently drop whatever change
+    /// notifications arrived between the original request and the retry.
+    pub(crate) fn retry_decision(
+        &self,
+        idempotency: retry::Idempotency,
+        error: &err::Driver,
+        attempt: u32,
+    ) -> retry::RetryDecision {
+        if self.is_change_feed() {
+            return retry::RetryDecision::DontRetry;
+        }
+        let decision = self.retry_policy.decide(idempotency, error, attempt);
+        #[cfg(feature = "metrics")]
+        if decision != retry::RetryDecision::DontRetry {
+            self.metrics.record_retry();
+        }
+        decision
+    }
+
+    /// How long to wait before acting on a [`retry::RetryDecision`] that
+    /// approved a retry.
+    pub(crate) fn retry_backoff(&self, attempt: u32) -> std::time::Duration {
+        self.retry_policy.backoff(attempt)
     }
 
     /// Change the default database on this connection
@@ -202,20 +283,96 @@ impl Session {
         Ok(info)
     }
 
-    fn mark_broken(&self) {
-        self.broken.store(true, Ordering::SeqCst);
+    /// Re-runs driver-side node discovery against the `rethinkdb.server_status`
+    /// system table, dials any newly reported node that isn't already
+    /// tracked, and returns the addresses currently reported by the cluster.
+    /// Nodes this session already tracks keep their pools and health state
+    /// untouched; a newly discovered node is dialed with the same pool size
+    /// and transport every other node in this session uses.
+    pub async fn discover_nodes(&mut self) -> Result<Vec<SocketAddr>> {
+        let mut conn = self.connection()?;
+        let query = r.db("rethinkdb").table("server_status");
+        let payload = Payload(QueryType::Start, Some(query), Default::default());
+        trace!("discovering cluster nodes; token: {}", conn.token);
+        let (typ, resp) = conn.request(&payload, false).await?;
+        trace!(
+            "session.discover_nodes() run; token: {}, response type: {:?}",
+            conn.token,
+            typ,
+        );
+        // `conn` borrows `self`; release it before `self.nodes` is extended below.
+        drop(conn);
+        let statuses = serde_json::from_value::<Vec<serde_json::Value>>(resp.r)?;
+        let mut addrs = Vec::new();
+        for status in statuses {
+            let host = status["network"]["canonical_addresses"]
+                .as_array()
+                .and_then(|list| list.first())
+                .and_then(|entry| entry["host"].as_str());
+            let port = status["network"]["reql_port"].as_u64().unwrap_or(28015) as u16;
+            if let Some(Ok(ip)) = host.map(|h| h.parse()) {
+                addrs.push(SocketAddr::new(ip, port));
+            }
+        }
+
+        for &addr in &addrs {
+            if self.nodes.iter().any(|node| node.addr() == addr) {
+                continue;
+            }
+            match pool::ConnectionPool::connect(addr, self.pool_size, self.transport.clone()).await {
+                Ok(new_pool) => {
+                    if let Err(error) =
+                        cmd::connect::handshake_pool(&new_pool, self.authenticator.as_ref()).await
+                    {
+                        trace!("failed to authenticate newly discovered node {}: {}", addr, error);
+                        continue;
+                    }
+                    self.nodes.push(Node { addr, pool: new_pool });
+                }
+                Err(error) => trace!("failed to dial newly discovered node {}: {}", addr, error),
+            }
+        }
+
+        Ok(addrs)
+    }
+
+    /// A snapshot of driver metrics: total queries, errors, retries, the
+    /// current in-flight count (derived from the channel map rather than
+    /// tracked separately), and p50/p99 latency estimates around
+    /// [`Connection::request`]. Only present when built with the `metrics`
+    /// feature, so sessions that don't ask for it pay nothing.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> metrics::MetricsSnapshot {
+        self.metrics.snapshot(self.channels.len() as u64)
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn record_query(&self, latency: std::time::Duration) {
+        self.metrics.record_query(latency);
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn record_error(&self, error: &Error) {
+        self.metrics.record_error(error);
     }
 
     fn broken(&self) -> Result<()> {
-        if self.broken.load(Ordering::SeqCst) {
+        if self.is_broken() {
             return Err(err::Driver::ConnectionBroken.into());
         }
         Ok(())
     }
 
+    /// `true` once the session can no longer serve queries: either every
+    /// node's pool is down, or the 64-bit token space has been exhausted.
+    /// A single dropped socket, or even a single dropped node, no longer
+    /// trips this while other sockets are up: new tokens are routed around
+    /// it. A broken socket is re-established only when a request on it fails
+    /// and the retry policy asks for a new connection; once every socket is
+    /// down, nothing reconnects them and the session stays broken.
     #[doc(hidden)]
     pub fn is_broken(&self) -> bool {
-        self.broken.load(Ordering::SeqCst)
+        self.exhausted.load(Ordering::SeqCst) || self.nodes.iter().all(|node| node.pool.is_broken())
     }
 
     fn mark_change_feed(&self) {
@@ -243,17 +400,34 @@ pub struct Connection<'a> {
     session: &'a Session,
     rx: Arc<Mutex<Receiver>>,
     token: u64,
+    node_index: cluster::NodeIndex,
+    pool_index: usize,
 }
 
 impl<'a> Connection<'a> {
-    fn new(session: &'a Session, rx: Receiver, token: u64) -> Connection<'a> {
+    fn new(
+        session: &'a Session,
+        rx: Receiver,
+        token: u64,
+        node_index: cluster::NodeIndex,
+        pool_index: usize,
+    ) -> Connection<'a> {
         Connection {
             session,
             token,
+            node_index,
+            pool_index,
             rx: Arc::new(Mutex::new(rx)),
         }
     }
 
+    /// The node and pool-slot index that this token's traffic is sharded
+    /// onto. Used by the read/write machinery to pick the right socket and
+    /// to lazily reconnect it if it was marked broken.
+    pub(crate) fn pool_index(&self) -> (cluster::NodeIndex, usize) {
+        (self.node_index, self.pool_index)
+    }
+
     /// Close an open connection
     ///
     /// ## Example
@@ -296,6 +470,48 @@ impl<'a> Connection<'a> {
         );
         Ok(())
     }
+
+    /// A lightweight, `Send + Sync` handle that can stop this connection's
+    /// in-flight query or changefeed from another task, without dropping
+    /// the whole `Connection`. Unlike [`close`](Self::close), which only
+    /// tears down a changefeed bound to `self`, this works for any query and
+    /// lets server-side computation be torn down promptly when a consumer
+    /// times out.
+    pub fn cancel_token(&self) -> CancelToken<'a> {
+        CancelToken {
+            session: self.session,
+            token: self.token,
+            node_index: self.node_index,
+            pool_index: self.pool_index,
+        }
+    }
+}
+
+/// See [`Connection::cancel_token`].
+#[derive(Debug, Clone)]
+pub struct CancelToken<'a> {
+    session: &'a Session,
+    token: u64,
+    node_index: cluster::NodeIndex,
+    pool_index: usize,
+}
+
+impl<'a> CancelToken<'a> {
+    /// Sends a `QueryType::Stop` for this token so the server drops whatever
+    /// it was computing for it.
+    ///
+    /// This writes directly to the token's pooled socket rather than opening
+    /// a second `Connection` for it: the owning `Connection` already has a
+    /// channel registered for `token`, and standing up another one here
+    /// would overwrite that registration (orphaning the owning connection's
+    /// receiver) and then, on drop, remove it from the session's demux map
+    /// entirely. The server's response to the stop still carries `token`
+    /// and is delivered through the owning connection's existing channel.
+    pub async fn cancel(&self) -> Result<()> {
+        let payload = Payload(QueryType::Stop, None, Default::default());
+        trace!("cancelling query; token: {}", self.token);
+        cmd::run::send_stop(self.session, self.node_index, self.pool_index, self.token, &payload).await
+    }
 }
 
 impl Drop for Connection<'_> {
@@ -322,6 +538,11 @@ pub struct r;
 impl r {
     /// Create a new connection to the database server
     ///
+    /// The V1.0 handshake authenticates with SCRAM-SHA-256, using whatever
+    /// [`auth::AuthenticatorProvider`] is configured on the connect options
+    /// ([`auth::StaticCredentials`] by default) rather than a hard-wired
+    /// username/password exchange.
+    ///
     /// # Example
     ///
     /// Open a connection using the default host and port, specifying the default database.
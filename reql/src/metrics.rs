@@ -0,0 +1,201 @@
+//! Optional driver metrics, feature-gated so a `Session` that doesn't ask
+//! for them pays no overhead.
+//!
+//! [`Metrics`] is a small registry of atomic counters plus a mutex-guarded
+//! latency histogram that a [`Session`](crate::Session)
+//! can hold; [`Connection::request`](crate::Connection) records into it
+//! around every round trip when present.
+
+use crate::err::{Driver, Error};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// A point-in-time read of a [`Metrics`] registry.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetricsSnapshot {
+    pub queries: u64,
+    pub errors: u64,
+    /// Of `errors`, how many were connection-level (broken socket, timeout,
+    /// no nodes available) rather than something the server reported.
+    pub connection_errors: u64,
+    /// Of `errors`, how many weren't connection-level — server-reported
+    /// errors, authentication failures, (de)serialization, and so on.
+    pub other_errors: u64,
+    pub retries: u64,
+    pub in_flight: u64,
+    pub latency_p50_micros: u64,
+    pub latency_p99_micros: u64,
+}
+
+/// Records query counts, errors (by coarse category), retries and
+/// per-query latency for a [`Session`](crate::Session). `in_flight` is
+/// derived from the session's channel map rather than tracked here, since
+/// that count already exists.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    queries: AtomicU64,
+    errors: AtomicU64,
+    connection_errors: AtomicU64,
+    other_errors: AtomicU64,
+    retries: AtomicU64,
+    histogram: Mutex<LatencyHistogram>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record_query(&self, latency: std::time::Duration) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.histogram.lock().unwrap().record(latency);
+    }
+
+    /// Records a failed request, classifying it as connection-level or
+    /// "other" so an operator can tell which kind of failure is occurring.
+    pub(crate) fn record_error(&self, error: &Error) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        let counter = match error {
+            Error::Driver(Driver::ConnectionBroken | Driver::TimedOut | Driver::NoNodesAvailable) => {
+                &self.connection_errors
+            }
+            _ => &self.other_errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, in_flight: u64) -> MetricsSnapshot {
+        let (p50, p99) = self.histogram.lock().unwrap().percentiles();
+        MetricsSnapshot {
+            queries: self.queries.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            connection_errors: self.connection_errors.load(Ordering::Relaxed),
+            other_errors: self.other_errors.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            in_flight,
+            latency_p50_micros: p50,
+            latency_p99_micros: p99,
+        }
+    }
+}
+
+/// A coarse, fixed-bucket latency histogram. Not as precise as a proper
+/// HDR histogram, but dependency-free and good enough for p50/p99
+/// estimates of connection-multiplexing contention.
+#[derive(Debug)]
+struct LatencyHistogram {
+    // Count of recorded latencies per bucket; bucket `i` holds latencies up
+    // to `BOUNDS_MICROS[i]` micros, and the last bucket catches everything
+    // above `BOUNDS_MICROS`'s final entry.
+    buckets: [u64; Self::BOUNDS_MICROS.len() + 1],
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; Self::BOUNDS_MICROS.len() + 1],
+        }
+    }
+}
+
+impl LatencyHistogram {
+    const BOUNDS_MICROS: [u64; 12] = [
+        100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    ];
+
+    fn record(&mut self, latency: std::time::Duration) {
+        let micros = latency.as_micros() as u64;
+        let idx = Self::BOUNDS_MICROS
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(Self::BOUNDS_MICROS.len());
+        self.buckets[idx] += 1;
+    }
+
+    fn percentile(&self, p: f64) -> u64 {
+        let total: u64 = self.buckets.iter().sum();
+        if total == 0 {
+            return 0;
+        }
+        let target = (total as f64 * p).ceil() as u64;
+        let mut seen = 0u64;
+        for (idx, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return *Self::BOUNDS_MICROS.get(idx).unwrap_or(&Self::BOUNDS_MICROS[Self::BOUNDS_MICROS.len() - 1]);
+            }
+        }
+        Self::BOUNDS_MICROS[Self::BOUNDS_MICROS.len() - 1]
+    }
+
+    fn percentiles(&self) -> (u64, u64) {
+        (self.percentile(0.50), self.percentile(0.99))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn empty_histogram_reports_zero_percentiles() {
+        let histogram = LatencyHistogram::default();
+        assert_eq!(histogram.percentiles(), (0, 0));
+    }
+
+    #[test]
+    fn percentiles_land_in_the_bucket_bound_above_the_recorded_latency() {
+        let mut histogram = LatencyHistogram::default();
+        for _ in 0..9 {
+            histogram.record(Duration::from_micros(50));
+        }
+        histogram.record(Duration::from_micros(400_000));
+
+        let (p50, p99) = histogram.percentiles();
+        assert_eq!(p50, 100);
+        assert_eq!(p99, 500_000);
+    }
+
+    #[test]
+    fn latency_above_the_largest_bound_falls_into_the_overflow_bucket() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_secs(10));
+        let (p50, p99) = histogram.percentiles();
+        assert_eq!(p50, 500_000);
+        assert_eq!(p99, 500_000);
+    }
+
+    #[test]
+    fn metrics_snapshot_reflects_recorded_activity() {
+        let metrics = Metrics::new();
+        metrics.record_query(Duration::from_micros(50));
+        metrics.record_query(Duration::from_micros(50));
+        metrics.record_error(&Error::Driver(Driver::ConnectionBroken));
+        metrics.record_retry();
+
+        let snapshot = metrics.snapshot(3);
+        assert_eq!(snapshot.queries, 2);
+        assert_eq!(snapshot.errors, 1);
+        assert_eq!(snapshot.connection_errors, 1);
+        assert_eq!(snapshot.other_errors, 0);
+        assert_eq!(snapshot.retries, 1);
+        assert_eq!(snapshot.in_flight, 3);
+        assert_eq!(snapshot.latency_p50_micros, 100);
+    }
+
+    #[test]
+    fn metrics_snapshot_distinguishes_other_errors_from_connection_errors() {
+        let metrics = Metrics::new();
+        metrics.record_error(&Error::Driver(Driver::Authentication("bad password".into())));
+
+        let snapshot = metrics.snapshot(0);
+        assert_eq!(snapshot.errors, 1);
+        assert_eq!(snapshot.connection_errors, 0);
+        assert_eq!(snapshot.other_errors, 1);
+    }
+}
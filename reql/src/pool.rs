@@ -0,0 +1,161 @@
+//! A small pool of physical connections shared by a [`Session`](crate::Session).
+//!
+//! Previously all traffic serialized through a single `Mutex<TcpStream>`, so
+//! head-of-line blocking on one big cursor stalled every other query on the
+//! connection. A [`ConnectionPool`] keeps a handful of sockets open instead
+//! and hands them out round-robin, so one slow socket only blocks the tokens
+//! registered on it. Sockets are transport-agnostic: plain TCP by default,
+//! or TLS when the pool was built with a [`Transport::Tls`].
+
+use async_net::TcpStream;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadHalf, WriteHalf};
+use futures::lock::Mutex;
+use std::fmt;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::Result;
+#[cfg(feature = "rustls")]
+use crate::tls::TlsConfig;
+
+/// A socket that can be read from and written to, regardless of whether
+/// it's a plain `TcpStream` or one wrapped in TLS.
+pub(crate) trait AsyncStream: AsyncRead + AsyncWrite + Send {}
+impl<T: AsyncRead + AsyncWrite + Send> AsyncStream for T {}
+
+pub(crate) type BoxedStream = Pin<Box<dyn AsyncStream>>;
+
+/// How a [`ConnectionPool`] should (re)establish its sockets.
+#[derive(Debug, Clone)]
+pub(crate) enum Transport {
+    Plain,
+    #[cfg(feature = "rustls")]
+    Tls(TlsConfig),
+}
+
+impl Transport {
+    async fn connect(&self, addr: SocketAddr) -> Result<BoxedStream> {
+        let tcp = TcpStream::connect(addr).await?;
+        match self {
+            Transport::Plain => Ok(Box::pin(tcp)),
+            #[cfg(feature = "rustls")]
+            Transport::Tls(config) => Ok(Box::pin(crate::tls::connect(tcp, config).await?)),
+        }
+    }
+}
+
+/// One physical socket in a [`ConnectionPool`] plus its own health flag.
+///
+/// A dead socket only takes itself out of rotation here; it no longer
+/// poisons every other connection the way the old session-wide `broken`
+/// flag did. The read and write halves are split and locked independently so
+/// that writing a `QueryType::Stop` frame (see
+/// [`cmd::run::send_stop`](crate::cmd::run::send_stop)) doesn't have to wait
+/// behind a blocking read on the very query it's trying to interrupt.
+pub(crate) struct PooledConnection {
+    pub(crate) reader: Mutex<ReadHalf<BoxedStream>>,
+    pub(crate) writer: Mutex<WriteHalf<BoxedStream>>,
+    broken: AtomicBool,
+}
+
+impl fmt::Debug for PooledConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledConnection")
+            .field("broken", &self.is_broken())
+            .finish()
+    }
+}
+
+impl PooledConnection {
+    fn new(stream: BoxedStream) -> Self {
+        let (reader, writer) = stream.split();
+        Self {
+            reader: Mutex::new(reader),
+            writer: Mutex::new(writer),
+            broken: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn mark_broken(&self) {
+        self.broken.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_broken(&self) -> bool {
+        self.broken.load(Ordering::SeqCst)
+    }
+}
+
+/// A round-robined set of physical connections to a single RethinkDB host.
+pub(crate) struct ConnectionPool {
+    addr: SocketAddr,
+    transport: Transport,
+    connections: Vec<PooledConnection>,
+    next: AtomicUsize,
+}
+
+impl fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("addr", &self.addr)
+            .field("connections", &self.connections)
+            .finish()
+    }
+}
+
+impl ConnectionPool {
+    pub(crate) async fn connect(addr: SocketAddr, size: usize, transport: Transport) -> Result<Self> {
+        let size = size.max(1);
+        let mut connections = Vec::with_capacity(size);
+        for _ in 0..size {
+            connections.push(PooledConnection::new(transport.connect(addr).await?));
+        }
+        Ok(Self {
+            addr,
+            transport,
+            connections,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Picks the index of the next connection to register a token's channel
+    /// on, round-robin. Connections already marked broken are skipped when a
+    /// healthy one is available. A broken connection is re-established only
+    /// by [`reconnect`](Self::reconnect), which the request path calls when
+    /// a request on it fails and the retry policy asks for a new connection.
+    pub(crate) fn pick(&self) -> usize {
+        let len = self.connections.len();
+        for _ in 0..len {
+            let idx = self.next.fetch_add(1, Ordering::Relaxed) % len;
+            if !self.connections[idx].is_broken() {
+                return idx;
+            }
+        }
+        self.next.fetch_add(1, Ordering::Relaxed) % len
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> &PooledConnection {
+        &self.connections[idx]
+    }
+
+    /// Lazily re-establishes a connection that was previously marked broken,
+    /// using the same transport (plain or TLS) the pool was created with.
+    pub(crate) async fn reconnect(&self, idx: usize) -> Result<()> {
+        let conn = &self.connections[idx];
+        let stream = self.transport.connect(self.addr).await?;
+        let (reader, writer) = stream.split();
+        *conn.reader.lock().await = reader;
+        *conn.writer.lock().await = writer;
+        conn.broken.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// `true` once every connection in the pool is down.
+    pub(crate) fn is_broken(&self) -> bool {
+        self.connections.iter().all(|c| c.is_broken())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.connections.len()
+    }
+}
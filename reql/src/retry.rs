@@ -0,0 +1,183 @@
+//! Retry policy for transient failures.
+//!
+//! [`Connection::request`](crate::Connection) consults a [`RetryPolicy`]
+//! before surfacing an error to the caller, so transient connection-level
+//! failures can be retried without every caller reimplementing backoff.
+
+use crate::err::Driver;
+use ql2::term::TermType;
+use std::time::Duration;
+
+/// Whether a query is safe to retry automatically.
+///
+/// Reads (`Get`, `Filter`, `Table`, ...) are idempotent; writes (`Insert`,
+/// `Update`, `Delete`, ...) are not unless the caller opts in explicitly via
+/// [`cmd::run::Options::idempotent`](crate::cmd::run::Options::idempotent).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idempotency {
+    Idempotent,
+    NonIdempotent,
+}
+
+impl Idempotency {
+    /// Classifies a query by its top-level term, the same way the rest of
+    /// the driver inspects `TermType` to decide how to serialize a query.
+    pub fn of(term: TermType, forced: bool) -> Self {
+        if forced {
+            return Idempotency::Idempotent;
+        }
+        match term {
+            TermType::Insert
+            | TermType::Update
+            | TermType::Replace
+            | TermType::Delete
+            | TermType::ForEach => Idempotency::NonIdempotent,
+            _ => Idempotency::Idempotent,
+        }
+    }
+}
+
+/// What a [`RetryPolicy`] decided to do about a failed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Retry on the same physical connection.
+    RetrySameConnection,
+    /// Retry, but re-establish the connection first.
+    RetryNewConnection,
+    /// Surface the error to the caller.
+    DontRetry,
+}
+
+/// Decides whether a failed request should be retried.
+pub trait RetryPolicy: std::fmt::Debug + Send + Sync {
+    /// `attempt` is the number of retries already made (0 for the first
+    /// failure).
+    fn decide(&self, idempotency: Idempotency, error: &Driver, attempt: u32) -> RetryDecision;
+
+    /// How long to wait before the retry `decide` just approved.
+    fn backoff(&self, attempt: u32) -> Duration;
+}
+
+/// Retries connection-level and timeout errors on a fresh connection, with
+/// capped exponential backoff, up to `max_retries` times. Never retries a
+/// non-idempotent write. Changefeeds are never retried either: the caller
+/// filters them out before the policy is ever consulted.
+#[derive(Debug, Clone)]
+pub struct DefaultRetryPolicy {
+    pub max_retries: u32,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for DefaultRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy for DefaultRetryPolicy {
+    fn decide(&self, idempotency: Idempotency, error: &Driver, attempt: u32) -> RetryDecision {
+        if attempt >= self.max_retries {
+            return RetryDecision::DontRetry;
+        }
+        if idempotency == Idempotency::NonIdempotent {
+            return RetryDecision::DontRetry;
+        }
+        match error {
+            Driver::ConnectionBroken | Driver::TimedOut => RetryDecision::RetryNewConnection,
+            _ => RetryDecision::DontRetry,
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let doubled = self.base_backoff.saturating_mul(1 << attempt.min(16));
+        doubled.min(self.max_backoff)
+    }
+}
+
+/// Never retries; surfaces every error to the caller immediately.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoRetryPolicy;
+
+impl RetryPolicy for NoRetryPolicy {
+    fn decide(&self, _idempotency: Idempotency, _error: &Driver, _attempt: u32) -> RetryDecision {
+        RetryDecision::DontRetry
+    }
+
+    fn backoff(&self, _attempt: u32) -> Duration {
+        Duration::from_secs(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idempotency_of_classifies_writes_as_non_idempotent() {
+        assert_eq!(Idempotency::of(TermType::Insert, false), Idempotency::NonIdempotent);
+        assert_eq!(Idempotency::of(TermType::Update, false), Idempotency::NonIdempotent);
+        assert_eq!(Idempotency::of(TermType::Replace, false), Idempotency::NonIdempotent);
+        assert_eq!(Idempotency::of(TermType::Delete, false), Idempotency::NonIdempotent);
+        assert_eq!(Idempotency::of(TermType::ForEach, false), Idempotency::NonIdempotent);
+        assert_eq!(Idempotency::of(TermType::Get, false), Idempotency::Idempotent);
+        assert_eq!(Idempotency::of(TermType::Table, false), Idempotency::Idempotent);
+    }
+
+    #[test]
+    fn idempotency_of_respects_forced_override() {
+        assert_eq!(Idempotency::of(TermType::Insert, true), Idempotency::Idempotent);
+    }
+
+    #[test]
+    fn default_policy_retries_connection_level_errors_on_a_new_connection() {
+        let policy = DefaultRetryPolicy::default();
+        let decision = policy.decide(Idempotency::Idempotent, &Driver::ConnectionBroken, 0);
+        assert_eq!(decision, RetryDecision::RetryNewConnection);
+    }
+
+    #[test]
+    fn default_policy_never_retries_non_idempotent_writes() {
+        let policy = DefaultRetryPolicy::default();
+        let decision = policy.decide(Idempotency::NonIdempotent, &Driver::ConnectionBroken, 0);
+        assert_eq!(decision, RetryDecision::DontRetry);
+    }
+
+    #[test]
+    fn default_policy_stops_once_max_retries_is_reached() {
+        let policy = DefaultRetryPolicy::default();
+        let decision = policy.decide(Idempotency::Idempotent, &Driver::ConnectionBroken, policy.max_retries);
+        assert_eq!(decision, RetryDecision::DontRetry);
+    }
+
+    #[test]
+    fn default_policy_does_not_retry_non_connection_errors() {
+        let policy = DefaultRetryPolicy::default();
+        let decision = policy.decide(
+            Idempotency::Idempotent,
+            &Driver::Authentication("bad password".into()),
+            0,
+        );
+        assert_eq!(decision, RetryDecision::DontRetry);
+    }
+
+    #[test]
+    fn default_policy_backoff_doubles_and_is_capped() {
+        let policy = DefaultRetryPolicy::default();
+        assert_eq!(policy.backoff(0), policy.base_backoff);
+        assert_eq!(policy.backoff(1), policy.base_backoff * 2);
+        assert_eq!(policy.backoff(2), policy.base_backoff * 4);
+        assert_eq!(policy.backoff(64), policy.max_backoff);
+    }
+
+    #[test]
+    fn no_retry_policy_never_retries() {
+        let policy = NoRetryPolicy;
+        let decision = policy.decide(Idempotency::Idempotent, &Driver::ConnectionBroken, 0);
+        assert_eq!(decision, RetryDecision::DontRetry);
+    }
+}
@@ -0,0 +1,83 @@
+//! Optional TLS transport, for talking to RethinkDB behind a TLS-terminating
+//! proxy or a cluster started with `--driver-tls`.
+//!
+//! Feature-gated behind `rustls` so the dependency (and its cost) is zero
+//! when TLS isn't used.
+
+/// TLS configuration for [`cmd::connect::Options::tls`](crate::cmd::connect::Options::tls).
+///
+/// Carries a CA certificate to verify the server against, an optional
+/// client certificate for mutual TLS, and the hostname used for SNI and
+/// certificate verification.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub(crate) ca_cert: Vec<u8>,
+    pub(crate) client_cert: Option<ClientCert>,
+    pub(crate) server_name: String,
+}
+
+/// A client certificate/key pair for mutual TLS.
+#[derive(Debug, Clone)]
+pub struct ClientCert {
+    pub(crate) cert: Vec<u8>,
+    pub(crate) key: Vec<u8>,
+}
+
+impl TlsConfig {
+    /// `ca_cert` and `server_name` are required; a client certificate for
+    /// mutual TLS can be added with [`client_cert`](Self::client_cert).
+    pub fn new(server_name: impl Into<String>, ca_cert: Vec<u8>) -> Self {
+        Self {
+            ca_cert,
+            client_cert: None,
+            server_name: server_name.into(),
+        }
+    }
+
+    pub fn client_cert(mut self, cert: Vec<u8>, key: Vec<u8>) -> Self {
+        self.client_cert = Some(ClientCert { cert, key });
+        self
+    }
+}
+
+#[cfg(feature = "rustls")]
+pub(crate) async fn connect(
+    tcp: async_net::TcpStream,
+    config: &TlsConfig,
+) -> crate::Result<impl futures::io::AsyncRead + futures::io::AsyncWrite + Send> {
+    use futures_rustls::rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore};
+    use futures_rustls::TlsConnector;
+    use std::convert::TryInto;
+    use std::sync::Arc;
+
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(&Certificate(config.ca_cert.clone()))
+        .map_err(|e| crate::err::Driver::Other(format!("invalid CA certificate: {e}")))?;
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+
+    let tls_config = match &config.client_cert {
+        Some(client) => builder
+            .with_client_auth_cert(
+                vec![Certificate(client.cert.clone())],
+                PrivateKey(client.key.clone()),
+            )
+            .map_err(|e| crate::err::Driver::Other(format!("invalid client certificate: {e}")))?,
+        None => builder.with_no_client_auth(),
+    };
+
+    let connector = TlsConnector::from(Arc::new(tls_config));
+    let server_name: futures_rustls::rustls::ServerName = config
+        .server_name
+        .as_str()
+        .try_into()
+        .map_err(|_| crate::err::Driver::Other("invalid TLS server name".into()))?;
+
+    connector
+        .connect(server_name, tcp)
+        .await
+        .map_err(|e| crate::err::Driver::Other(format!("TLS handshake failed: {e}")).into())
+}